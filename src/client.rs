@@ -1,5 +1,15 @@
 use std::fs::read;
-use rumqttc::{self, Incoming, Client, Connection, MqttOptions, Publish, PubAck, QoS, ConnectionError};
+use rumqttc::{self, Incoming, Client, ClientError, Connection, MqttOptions, Publish, PubAck, QoS, ConnectionError, LastWill, Protocol, Transport};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::sigv4::presigned_wss_url;
+
+/// Parses the JSON payload of `message`, for use alongside `publish_json`
+/// inside `AWSEventHandler::on_publish`/`AWSAsyncEventHandler::on_publish`.
+pub fn decode_publish<T: DeserializeOwned>(message: &Publish) -> serde_json::Result<T> {
+    serde_json::from_slice(&message.payload)
+}
 
 #[cfg(feature= "async")]
 use rumqttc::{EventLoop, Subscribe, Request};
@@ -8,31 +18,46 @@ use async_channel::Sender;
 
 pub trait AWSEventHandler {
 
-    fn on_connect() {
+    fn on_connect(&self) {
         println!("Default connection!");
     }
-    fn on_publish(message: Publish) {
+    fn on_publish(&self, message: Publish) {
         println!("Default publish");
     }
 
-    fn on_puback(message: PubAck) {
+    fn on_puback(&self, message: PubAck) {
         println!("Default puback");
     }
 
+    /// Called when the broker sends an MQTT DISCONNECT.
+    fn on_disconnect(&self) {
+        println!("Default disconnect");
+    }
+
+    /// Called whenever the connection loop returns an error, e.g. on a
+    /// dropped network. `rumqttc` keeps retrying internally, so a
+    /// successful reconnect shows up as another `on_connect()` call.
+    fn on_error(&self, error: &ConnectionError) {
+        println!("Default error: {:?}", error);
+    }
+
     fn start_event_listener(&self, mut connection: Connection) {
         for notification in connection.iter() {
             match notification {
                 Ok(notification_type) => match notification_type.0 {
                     Some(Incoming::Publish(message)) => {
-                        Self::on_publish(message);
+                        self.on_publish(message);
                     },
                     Some(Incoming::Connected) => {
-                        Self::on_connect();
+                        self.on_connect();
+                    },
+                    Some(Incoming::Disconnect) => {
+                        self.on_disconnect();
                     },
                     _ => (),
                     None => (),
                 },
-                Err(_) => (),
+                Err(error) => self.on_error(&error),
             }
         }
     }
@@ -42,17 +67,29 @@ pub trait AWSEventHandler {
 #[async_trait]
 pub trait AWSAsyncEventHandler {
 
-    fn on_connect() {
+    fn on_connect(&self) {
         println!("Default connection!");
     }
-    fn on_publish(message: Publish) {
+    fn on_publish(&self, message: Publish) {
         println!("Default publish");
     }
 
-    fn on_puback(message: PubAck) {
+    fn on_puback(&self, message: PubAck) {
         println!("Default puback");
     }
 
+    /// Called when the broker sends an MQTT DISCONNECT.
+    fn on_disconnect(&self) {
+        println!("Default disconnect");
+    }
+
+    /// Called whenever polling the event loop returns an error, e.g. on a
+    /// dropped network. `rumqttc` keeps retrying internally, so a
+    /// successful reconnect shows up as another `on_connect()` call.
+    fn on_error(&self, error: &ConnectionError) {
+        println!("Default error: {:?}", error);
+    }
+
     async fn start_async_event_listener(&self, mut eventloop: EventLoop) {
         loop {
             match eventloop.poll().await {
@@ -60,30 +97,54 @@ pub trait AWSAsyncEventHandler {
                     println!("Incoming message!");
                     match incoming.0 {
                         Some(Incoming::Publish(message)) => {
-                            Self::on_publish(message);
+                            self.on_publish(message);
                         },
                         Some(Incoming::Connected) => {
-                            Self::on_connect();
+                            self.on_connect();
                         },
                         Some(Incoming::PubAck(puback)) => {
-                            Self::on_puback(puback);
+                            self.on_puback(puback);
+                        },
+                        Some(Incoming::Disconnect) => {
+                            self.on_disconnect();
                         },
                         _ => (),
                     }
                 },
-                Err(_) => (),
+                Err(error) => self.on_error(&error),
             }
         }
     }
 
 }
 
+/// How to connect to the AWS IoT Device Gateway.
+pub enum ConnectionTransport {
+    /// mTLS over the default MQTT port 8883.
+    Mqtts,
+    /// mTLS over secure WebSockets on port 443, for networks that block 8883.
+    Wss,
+    /// Secure WebSockets on port 443, authenticated with a SigV4-presigned
+    /// URL instead of a client certificate.
+    WssSigv4 {
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+        region: String,
+    },
+}
+
 pub struct AWSIoTSettings {
         client_id: String,
         ca_path: String,
         client_cert_path: String,
         client_key_path: String,
         aws_iot_endpoint: String,
+        keep_alive_secs: u16,
+        clean_session: bool,
+        last_will: Option<LastWill>,
+        mqtt_version: Protocol,
+        transport: ConnectionTransport,
 }
 
 impl AWSIoTSettings {
@@ -94,9 +155,97 @@ impl AWSIoTSettings {
         client_key_path: String,
         aws_iot_endpoint: String) -> AWSIoTSettings {
 
-        AWSIoTSettings { client_id, ca_path, client_cert_path, client_key_path, aws_iot_endpoint }
-    
+        AWSIoTSettings {
+            client_id,
+            ca_path,
+            client_cert_path,
+            client_key_path,
+            aws_iot_endpoint,
+            keep_alive_secs: 10,
+            clean_session: true,
+            last_will: None,
+            mqtt_version: Protocol::V4,
+            transport: ConnectionTransport::Mqtts,
+        }
+    }
+
+    /// Sets the MQTT keep-alive interval, in seconds. Defaults to 10.
+    pub fn with_keep_alive(mut self, keep_alive_secs: u16) -> AWSIoTSettings {
+        self.keep_alive_secs = keep_alive_secs;
+        self
+    }
+
+    /// Sets whether the broker should discard any previous session state for
+    /// this client id on connect. Defaults to `true`.
+    pub fn with_clean_session(mut self, clean_session: bool) -> AWSIoTSettings {
+        self.clean_session = clean_session;
+        self
+    }
+
+    /// Sets a Last Will & Testament the broker publishes on this client's
+    /// behalf if it disconnects ungracefully, e.g. for AWS IoT presence
+    /// detection.
+    pub fn with_last_will(mut self, last_will: LastWill) -> AWSIoTSettings {
+        self.last_will = Some(last_will);
+        self
+    }
+
+    /// Sets the MQTT protocol version to negotiate. Defaults to
+    /// `Protocol::V4` (MQTT 3.1.1).
+    pub fn with_mqtt_version(mut self, mqtt_version: Protocol) -> AWSIoTSettings {
+        self.mqtt_version = mqtt_version;
+        self
+    }
+
+    /// Sets how to connect to the AWS IoT Device Gateway. Defaults to
+    /// `ConnectionTransport::Mqtts` (mTLS on port 8883).
+    pub fn with_transport(mut self, transport: ConnectionTransport) -> AWSIoTSettings {
+        self.transport = transport;
+        self
+    }
+}
+
+fn build_mqtt_options(settings: AWSIoTSettings) -> Result<MqttOptions, ConnectionError> {
+    let mut mqtt_options = match &settings.transport {
+        ConnectionTransport::WssSigv4 { access_key, secret_key, session_token, region } => {
+            let url = presigned_wss_url(
+                &settings.aws_iot_endpoint,
+                region,
+                access_key,
+                secret_key,
+                session_token.as_deref(),
+            );
+            let mut mqtt_options = MqttOptions::new(settings.client_id.clone(), url, 443);
+            mqtt_options.set_transport(Transport::wss(read(&settings.ca_path)?, None, None));
+            mqtt_options
+        }
+        ConnectionTransport::Mqtts => {
+            let mut mqtt_options =
+                MqttOptions::new(settings.client_id.clone(), settings.aws_iot_endpoint.clone(), 8883);
+            mqtt_options
+                .set_ca(read(&settings.ca_path)?)
+                .set_client_auth(read(&settings.client_cert_path)?, read(&settings.client_key_path)?);
+            mqtt_options
+        }
+        ConnectionTransport::Wss => {
+            let ca = read(&settings.ca_path)?;
+            let client_auth = (read(&settings.client_cert_path)?, read(&settings.client_key_path)?);
+            let mut mqtt_options =
+                MqttOptions::new(settings.client_id.clone(), settings.aws_iot_endpoint.clone(), 443);
+            mqtt_options.set_transport(Transport::wss(ca, Some(client_auth), None));
+            mqtt_options
+        }
+    };
+
+    mqtt_options
+        .set_keep_alive(settings.keep_alive_secs)
+        .set_clean_session(settings.clean_session)
+        .set_protocol(settings.mqtt_version);
+    if let Some(last_will) = settings.last_will {
+        mqtt_options.set_last_will(last_will);
     }
+
+    Ok(mqtt_options)
 }
 
 pub struct AWSIoTClient {
@@ -108,23 +257,59 @@ impl AWSIoTClient {
         settings: AWSIoTSettings
         ) -> Result<(AWSIoTClient, Connection), ConnectionError> {
 
-        let mut mqtt_options = MqttOptions::new(settings.client_id, settings.aws_iot_endpoint, 8883);
-        mqtt_options.set_ca(read(settings.ca_path)?)
-            .set_client_auth(read(settings.client_cert_path)?, read(settings.client_key_path)?)
-            .set_keep_alive(10);
+        let mqtt_options = build_mqtt_options(settings)?;
 
             let (client, connection) = Client::new(mqtt_options, 10);
             Ok((AWSIoTClient { client: client }, connection))
     }
 
-    /// Subscribe to any topic.
-    pub fn subscribe (&mut self, topic_name: String, qos: QoS) {
-        self.client.subscribe(topic_name, qos).unwrap();
+    /// Subscribe to any topic. Returns an error if the request could not be
+    /// queued, e.g. because the outgoing queue is full.
+    pub fn subscribe (&mut self, topic_name: String, qos: QoS) -> Result<(), ClientError> {
+        self.client.subscribe(topic_name, qos)
+    }
+
+    /// Publish to any topic. Returns an error if the request could not be
+    /// queued, e.g. because the outgoing queue is full.
+    pub fn publish (&mut self, topic_name: String, qos: QoS, payload: &str) -> Result<(), ClientError> {
+        self.client.publish(topic_name, qos, false, payload)
     }
 
-    /// Publish to any topic.
-    pub fn publish (&mut self, topic_name: String, qos: QoS, payload: &str) {
-        self.client.publish(topic_name, qos, false, payload).unwrap();
+    /// Serializes `payload` with `serde_json` and publishes it.
+    pub fn publish_json<T: Serialize>(&mut self, topic_name: String, qos: QoS, payload: &T) -> Result<(), PublishJsonError> {
+        let payload = serde_json::to_string(payload)?;
+        self.publish(topic_name, qos, &payload)?;
+        Ok(())
+    }
+}
+
+/// Error returned by [`AWSIoTClient::publish_json`].
+#[derive(Debug)]
+pub enum PublishJsonError {
+    Serialize(serde_json::Error),
+    Publish(ClientError),
+}
+
+impl std::fmt::Display for PublishJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublishJsonError::Serialize(err) => write!(f, "failed to serialize payload: {}", err),
+            PublishJsonError::Publish(err) => write!(f, "failed to publish: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PublishJsonError {}
+
+impl From<serde_json::Error> for PublishJsonError {
+    fn from(err: serde_json::Error) -> Self {
+        PublishJsonError::Serialize(err)
+    }
+}
+
+impl From<ClientError> for PublishJsonError {
+    fn from(err: ClientError) -> Self {
+        PublishJsonError::Publish(err)
     }
 }
 
@@ -137,30 +322,68 @@ pub struct AWSIoTAsyncClient {
 impl AWSIoTAsyncClient {
 
     pub async fn new(
-        client_id: &str,
-        ca_path: &str,
-        client_cert_path: &str,
-        client_key_path: &str,
-        aws_iot_endpoint: &str) -> Result<(AWSIoTAsyncClient, EventLoop), ConnectionError> {
-
-        let mut mqtt_options = MqttOptions::new(client_id, aws_iot_endpoint, 8883);
-        mqtt_options.set_ca(read(ca_path)?)
-            .set_client_auth(read(client_cert_path)?, read(client_key_path)?)
-            .set_keep_alive(10);
+        settings: AWSIoTSettings
+        ) -> Result<(AWSIoTAsyncClient, EventLoop), ConnectionError> {
+
+        let mqtt_options = build_mqtt_options(settings)?;
         let eventloop = EventLoop::new(mqtt_options, 10).await;
         let requests_tx = eventloop.handle();
         Ok((AWSIoTAsyncClient { sender: requests_tx }, eventloop))
     }
 
-    /// Subscribe to any topic.
-    pub async fn subscribe (&mut self, topic_name: String, qos: QoS) {
+    /// Subscribe to any topic. Returns an error if the event loop's request
+    /// channel has been closed.
+    pub async fn subscribe (&mut self, topic_name: String, qos: QoS) -> Result<(), async_channel::SendError<Request>> {
         let subscribe = Subscribe::new(topic_name, qos);
-        self.sender.send(Request::Subscribe(subscribe)).await.unwrap();
+        self.sender.send(Request::Subscribe(subscribe)).await
     }
 
-    /// Publish to any topic.
-    pub async fn publish (&mut self, topic_name: String, qos: QoS, payload: &str) {
+    /// Publish to any topic. Returns an error if the event loop's request
+    /// channel has been closed.
+    pub async fn publish (&mut self, topic_name: String, qos: QoS, payload: &str) -> Result<(), async_channel::SendError<Request>> {
         let publish = Publish::new(topic_name, qos, payload);
-        self.sender.send(Request::Publish(publish)).await.unwrap();
+        self.sender.send(Request::Publish(publish)).await
+    }
+
+    /// Serializes `payload` with `serde_json` and publishes it.
+    pub async fn publish_json<T: Serialize>(&mut self, topic_name: String, qos: QoS, payload: &T) -> Result<(), AsyncPublishJsonError> {
+        let payload = serde_json::to_string(payload)?;
+        self.publish(topic_name, qos, &payload).await?;
+        Ok(())
+    }
+}
+
+/// Error returned by [`AWSIoTAsyncClient::publish_json`].
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub enum AsyncPublishJsonError {
+    Serialize(serde_json::Error),
+    Publish(async_channel::SendError<Request>),
+}
+
+#[cfg(feature = "async")]
+impl std::fmt::Display for AsyncPublishJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsyncPublishJsonError::Serialize(err) => write!(f, "failed to serialize payload: {}", err),
+            AsyncPublishJsonError::Publish(err) => write!(f, "failed to publish: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::error::Error for AsyncPublishJsonError {}
+
+#[cfg(feature = "async")]
+impl From<serde_json::Error> for AsyncPublishJsonError {
+    fn from(err: serde_json::Error) -> Self {
+        AsyncPublishJsonError::Serialize(err)
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<async_channel::SendError<Request>> for AsyncPublishJsonError {
+    fn from(err: async_channel::SendError<Request>) -> Self {
+        AsyncPublishJsonError::Publish(err)
     }
 }
@@ -0,0 +1,13 @@
+pub mod client;
+pub mod router;
+pub mod shadow;
+pub mod sigv4;
+
+pub use client::*;
+pub use router::{topic_matches, TopicRouter};
+pub use shadow::{
+    parse_shadow_accepted, parse_shadow_delete_accepted, parse_shadow_rejected, DeviceShadow, ShadowDeleteResponse,
+    ShadowDocument, ShadowError, ShadowStateDocument,
+};
+#[cfg(feature = "async")]
+pub use shadow::AsyncDeviceShadow;
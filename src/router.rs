@@ -0,0 +1,117 @@
+use rumqttc::Publish;
+
+/// Returns whether the MQTT topic `topic` matches the subscription `filter`,
+/// honouring the `+` (single level) and `#` (multi level, trailing only)
+/// wildcards as defined by the MQTT spec.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter_segments = filter.split('/');
+    let mut topic_segments = topic.split('/');
+
+    loop {
+        match (filter_segments.next(), topic_segments.next()) {
+            (Some("#"), _) => return filter_segments.next().is_none(),
+            (Some("+"), Some(_)) => continue,
+            (Some(f), Some(t)) => {
+                if f != t {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+type Handler = Box<dyn Fn(&Publish) + Send + Sync>;
+
+struct Route {
+    filter: String,
+    handler: Handler,
+    once: bool,
+}
+
+/// Dispatches incoming `Publish` messages to handlers registered against
+/// MQTT topic filters, so callers don't have to string-match topics inside
+/// a single `on_publish`. Supports `+`/`#` wildcards, e.g.
+/// `floor/+/room/+/temperature`.
+#[derive(Default)]
+pub struct TopicRouter {
+    routes: Vec<Route>,
+}
+
+impl TopicRouter {
+    pub fn new() -> TopicRouter {
+        TopicRouter { routes: Vec::new() }
+    }
+
+    /// Registers `handler` to be called for every incoming message whose
+    /// topic matches `filter`.
+    pub fn on<F>(&mut self, filter: impl Into<String>, handler: F)
+    where
+        F: Fn(&Publish) + Send + Sync + 'static,
+    {
+        self.routes.push(Route { filter: filter.into(), handler: Box::new(handler), once: false });
+    }
+
+    /// Registers `handler` to be called for the first incoming message whose
+    /// topic matches `filter`, then deregisters it. Useful for one-shot
+    /// request/response exchanges (e.g. Device Shadow operations) where a
+    /// fresh handler is registered per call and must not linger or fire for
+    /// a later caller's response.
+    pub fn on_once<F>(&mut self, filter: impl Into<String>, handler: F)
+    where
+        F: Fn(&Publish) + Send + Sync + 'static,
+    {
+        self.routes.push(Route { filter: filter.into(), handler: Box::new(handler), once: true });
+    }
+
+    /// Calls every handler whose registered filter matches `message`'s topic,
+    /// then deregisters any one-shot handlers that just fired.
+    pub fn dispatch(&mut self, message: &Publish) {
+        let mut fired_once = Vec::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            if topic_matches(&route.filter, &message.topic) {
+                (route.handler)(message);
+                if route.once {
+                    fired_once.push(index);
+                }
+            }
+        }
+        for index in fired_once.into_iter().rev() {
+            self.routes.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_filters_match_only_the_same_topic() {
+        assert!(topic_matches("a/b/c", "a/b/c"));
+        assert!(!topic_matches("a/b/c", "a/b/d"));
+    }
+
+    #[test]
+    fn plus_matches_exactly_one_segment() {
+        assert!(topic_matches("floor/+/room/+/temperature", "floor/1/room/2/temperature"));
+        assert!(!topic_matches("floor/+/room/+/temperature", "floor/1/room/2/humidity"));
+        assert!(!topic_matches("a/+", "a"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+    }
+
+    #[test]
+    fn hash_matches_zero_or_more_trailing_segments() {
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("a/#", "a/b"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("#", "anything/at/all"));
+    }
+
+    #[test]
+    fn mismatched_segment_counts_without_wildcards_do_not_match() {
+        assert!(!topic_matches("a/b", "a/b/c"));
+        assert!(!topic_matches("a/b/c", "a/b"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rumqttc::{Client, ClientError, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::AWSIoTClient;
+use crate::router::TopicRouter;
+
+#[cfg(feature = "async")]
+use rumqttc::Request;
+#[cfg(feature = "async")]
+use async_channel::{SendError, Sender};
+#[cfg(feature = "async")]
+use crate::client::AWSIoTAsyncClient;
+
+/// The `state` object of a shadow document, as reported by the accepted topics
+/// and as sent on `update`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShadowStateDocument {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reported: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<Value>,
+}
+
+/// The payload published on `.../shadow/get/accepted` and
+/// `.../shadow/update/accepted`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShadowDocument {
+    pub state: ShadowStateDocument,
+    pub metadata: Value,
+    pub version: u64,
+    pub timestamp: u64,
+}
+
+/// The payload published on `.../shadow/delete/accepted`, which carries no
+/// `state`/`metadata`, unlike the `get`/`update` accepted payloads.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShadowDeleteResponse {
+    pub version: u64,
+    pub timestamp: u64,
+}
+
+/// The payload published on `.../rejected` when a shadow operation fails,
+/// e.g. because the shadow does not exist yet or the update was malformed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShadowError {
+    pub code: u16,
+    pub message: String,
+}
+
+fn shadow_topic(thing_name: &str, shadow_name: &Option<String>, action: &str) -> String {
+    match shadow_name {
+        Some(name) => format!("$aws/things/{}/shadow/name/{}/{}", thing_name, name, action),
+        None => format!("$aws/things/{}/shadow/{}", thing_name, action),
+    }
+}
+
+/// Parses a `get`/`update` shadow `.../accepted` payload into a [`ShadowDocument`].
+pub fn parse_shadow_accepted(payload: &[u8]) -> serde_json::Result<ShadowDocument> {
+    serde_json::from_slice(payload)
+}
+
+/// Parses a `.../shadow/delete/accepted` payload into a [`ShadowDeleteResponse`].
+pub fn parse_shadow_delete_accepted(payload: &[u8]) -> serde_json::Result<ShadowDeleteResponse> {
+    serde_json::from_slice(payload)
+}
+
+/// Parses a shadow `.../rejected` payload into a [`ShadowError`].
+pub fn parse_shadow_rejected(payload: &[u8]) -> serde_json::Result<ShadowError> {
+    serde_json::from_slice(payload)
+}
+
+/// Registers `on_result` against `router` so it fires once for whichever of
+/// `{topic}/accepted` or `{topic}/rejected` arrives first, decoded with
+/// `parse_accepted` or into a [`ShadowError`] respectively. The handler for
+/// the topic that fired is deregistered at once; the one for the other topic
+/// stays registered until a message arrives on it, and is then removed
+/// without calling `on_result`. Malformed payloads are dropped rather than
+/// passed to `on_result`.
+fn route_shadow_response<T, P, F>(router: &mut TopicRouter, topic: &str, parse_accepted: P, on_result: F)
+where
+    T: 'static,
+    P: Fn(&[u8]) -> serde_json::Result<T> + Send + Sync + 'static,
+    F: Fn(Result<T, ShadowError>) + Send + Sync + 'static,
+{
+    let on_result = Arc::new(on_result);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let accepted_result = on_result.clone();
+    let accepted_done = done.clone();
+    router.on_once(format!("{}/accepted", topic), move |message| {
+        if let Ok(document) = parse_accepted(&message.payload) {
+            if !accepted_done.swap(true, Ordering::SeqCst) {
+                accepted_result(Ok(document));
+            }
+        }
+    });
+
+    let rejected_result = on_result;
+    let rejected_done = done;
+    router.on_once(format!("{}/rejected", topic), move |message| {
+        if let Ok(error) = parse_shadow_rejected(&message.payload) {
+            if !rejected_done.swap(true, Ordering::SeqCst) {
+                rejected_result(Err(error));
+            }
+        }
+    });
+}
+
+/// A Device Shadow for a single thing (or named shadow), built on top of an
+/// [`AWSIoTClient`]. `get`/`update`/`delete` subscribe to the paired
+/// `accepted`/`rejected` response topics and register `on_result` with a
+/// [`TopicRouter`], so it fires once with the parsed [`ShadowDocument`]
+/// (or, for `delete`, [`ShadowDeleteResponse`]) or [`ShadowError`] when the
+/// matching response arrives. Call `router.dispatch(&message)` from your
+/// `AWSEventHandler::on_publish`.
+pub struct DeviceShadow {
+    client: Client,
+    thing_name: String,
+    shadow_name: Option<String>,
+}
+
+impl DeviceShadow {
+    /// Creates a shadow for the unnamed (classic) shadow of `thing_name`.
+    pub fn new(client: &AWSIoTClient, thing_name: String) -> DeviceShadow {
+        DeviceShadow { client: client.client.clone(), thing_name, shadow_name: None }
+    }
+
+    /// Creates a shadow for the named shadow `shadow_name` of `thing_name`.
+    pub fn named(client: &AWSIoTClient, thing_name: String, shadow_name: String) -> DeviceShadow {
+        DeviceShadow { client: client.client.clone(), thing_name, shadow_name: Some(shadow_name) }
+    }
+
+    fn topic(&self, action: &str) -> String {
+        shadow_topic(&self.thing_name, &self.shadow_name, action)
+    }
+
+    fn subscribe_responses(&mut self, action: &str) -> Result<(), ClientError> {
+        self.client.subscribe(format!("{}/accepted", self.topic(action)), QoS::AtLeastOnce)?;
+        self.client.subscribe(format!("{}/rejected", self.topic(action)), QoS::AtLeastOnce)?;
+        Ok(())
+    }
+
+    /// Requests the current shadow document, calling `on_result` once
+    /// `.../shadow/get/accepted` or `.../shadow/get/rejected` arrives.
+    pub fn get<F>(&mut self, router: &mut TopicRouter, on_result: F) -> Result<(), ClientError>
+    where
+        F: Fn(Result<ShadowDocument, ShadowError>) + Send + Sync + 'static,
+    {
+        self.subscribe_responses("get")?;
+        route_shadow_response(router, &self.topic("get"), parse_shadow_accepted, on_result);
+        self.client.publish(self.topic("get"), QoS::AtLeastOnce, false, "")
+    }
+
+    /// Publishes a new `desired` state, calling `on_result` once
+    /// `.../shadow/update/accepted` or `.../shadow/update/rejected` arrives.
+    pub fn update<F>(&mut self, router: &mut TopicRouter, desired: Value, on_result: F) -> Result<(), ClientError>
+    where
+        F: Fn(Result<ShadowDocument, ShadowError>) + Send + Sync + 'static,
+    {
+        self.subscribe_responses("update")?;
+        route_shadow_response(router, &self.topic("update"), parse_shadow_accepted, on_result);
+        let state = ShadowStateDocument { desired: Some(desired), reported: None, delta: None };
+        let payload = serde_json::json!({ "state": state }).to_string();
+        self.client.publish(self.topic("update"), QoS::AtLeastOnce, false, payload)
+    }
+
+    /// Deletes the shadow document, calling `on_result` once
+    /// `.../shadow/delete/accepted` or `.../shadow/delete/rejected` arrives.
+    pub fn delete<F>(&mut self, router: &mut TopicRouter, on_result: F) -> Result<(), ClientError>
+    where
+        F: Fn(Result<ShadowDeleteResponse, ShadowError>) + Send + Sync + 'static,
+    {
+        self.subscribe_responses("delete")?;
+        route_shadow_response(router, &self.topic("delete"), parse_shadow_delete_accepted, on_result);
+        self.client.publish(self.topic("delete"), QoS::AtLeastOnce, false, "")
+    }
+}
+
+/// The async equivalent of [`DeviceShadow`], built on top of an
+/// [`AWSIoTAsyncClient`].
+#[cfg(feature = "async")]
+pub struct AsyncDeviceShadow {
+    sender: Sender<Request>,
+    thing_name: String,
+    shadow_name: Option<String>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncDeviceShadow {
+    /// Creates a shadow for the unnamed (classic) shadow of `thing_name`.
+    pub fn new(client: &AWSIoTAsyncClient, thing_name: String) -> AsyncDeviceShadow {
+        AsyncDeviceShadow { sender: client.sender.clone(), thing_name, shadow_name: None }
+    }
+
+    /// Creates a shadow for the named shadow `shadow_name` of `thing_name`.
+    pub fn named(client: &AWSIoTAsyncClient, thing_name: String, shadow_name: String) -> AsyncDeviceShadow {
+        AsyncDeviceShadow { sender: client.sender.clone(), thing_name, shadow_name: Some(shadow_name) }
+    }
+
+    fn topic(&self, action: &str) -> String {
+        shadow_topic(&self.thing_name, &self.shadow_name, action)
+    }
+
+    async fn subscribe_responses(&mut self, action: &str) -> Result<(), SendError<Request>> {
+        use rumqttc::Subscribe;
+        let accepted = Subscribe::new(format!("{}/accepted", self.topic(action)), QoS::AtLeastOnce);
+        let rejected = Subscribe::new(format!("{}/rejected", self.topic(action)), QoS::AtLeastOnce);
+        self.sender.send(Request::Subscribe(accepted)).await?;
+        self.sender.send(Request::Subscribe(rejected)).await?;
+        Ok(())
+    }
+
+    /// Requests the current shadow document, calling `on_result` once
+    /// `.../shadow/get/accepted` or `.../shadow/get/rejected` arrives.
+    pub async fn get<F>(&mut self, router: &mut TopicRouter, on_result: F) -> Result<(), SendError<Request>>
+    where
+        F: Fn(Result<ShadowDocument, ShadowError>) + Send + Sync + 'static,
+    {
+        self.subscribe_responses("get").await?;
+        route_shadow_response(router, &self.topic("get"), parse_shadow_accepted, on_result);
+        let publish = rumqttc::Publish::new(self.topic("get"), QoS::AtLeastOnce, "");
+        self.sender.send(Request::Publish(publish)).await
+    }
+
+    /// Publishes a new `desired` state, calling `on_result` once
+    /// `.../shadow/update/accepted` or `.../shadow/update/rejected` arrives.
+    pub async fn update<F>(
+        &mut self,
+        router: &mut TopicRouter,
+        desired: Value,
+        on_result: F,
+    ) -> Result<(), SendError<Request>>
+    where
+        F: Fn(Result<ShadowDocument, ShadowError>) + Send + Sync + 'static,
+    {
+        self.subscribe_responses("update").await?;
+        route_shadow_response(router, &self.topic("update"), parse_shadow_accepted, on_result);
+        let state = ShadowStateDocument { desired: Some(desired), reported: None, delta: None };
+        let payload = serde_json::json!({ "state": state }).to_string();
+        let publish = rumqttc::Publish::new(self.topic("update"), QoS::AtLeastOnce, payload);
+        self.sender.send(Request::Publish(publish)).await
+    }
+
+    /// Deletes the shadow document, calling `on_result` once
+    /// `.../shadow/delete/accepted` or `.../shadow/delete/rejected` arrives.
+    pub async fn delete<F>(&mut self, router: &mut TopicRouter, on_result: F) -> Result<(), SendError<Request>>
+    where
+        F: Fn(Result<ShadowDeleteResponse, ShadowError>) + Send + Sync + 'static,
+    {
+        self.subscribe_responses("delete").await?;
+        route_shadow_response(router, &self.topic("delete"), parse_shadow_delete_accepted, on_result);
+        let publish = rumqttc::Publish::new(self.topic("delete"), QoS::AtLeastOnce, "");
+        self.sender.send(Request::Publish(publish)).await
+    }
+}
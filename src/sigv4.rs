@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use ring::{digest, hmac};
+
+const SERVICE: &str = "iotdevicegateway";
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Builds a SigV4-presigned `wss://` URL for connecting to the AWS IoT
+/// Device Gateway over WebSockets, per
+/// <https://docs.aws.amazon.com/general/latest/gr/signature-version-4.html>.
+/// The session token (if any) is intentionally appended after the signature
+/// rather than signed, matching how AWS IoT itself generates these URLs.
+pub fn presigned_wss_url(
+    endpoint: &str,
+    region: &str,
+    access_key: &str,
+    secret_key: &str,
+    session_token: Option<&str>,
+) -> String {
+    presigned_wss_url_at(endpoint, region, access_key, secret_key, session_token, Utc::now())
+}
+
+fn presigned_wss_url_at(
+    endpoint: &str,
+    region: &str,
+    access_key: &str,
+    secret_key: &str,
+    session_token: Option<&str>,
+    now: DateTime<Utc>,
+) -> String {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date_stamp = now.format("%Y%m%d").to_string();
+
+    let credential_scope = format!("{}/{}/{}/aws4_request", date_stamp, region, SERVICE);
+    let credential = format!("{}/{}", access_key, credential_scope);
+
+    let mut canonical_query = vec![
+        ("X-Amz-Algorithm".to_string(), ALGORITHM.to_string()),
+        ("X-Amz-Credential".to_string(), credential),
+        ("X-Amz-Date".to_string(), amz_date.clone()),
+        ("X-Amz-SignedHeaders".to_string(), "host".to_string()),
+    ];
+    canonical_query.sort();
+    let canonical_query_string = canonical_query
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k), uri_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "GET\n/mqtt\n{}\nhost:{}\n\nhost\n{}",
+        canonical_query_string,
+        endpoint,
+        sha256_hex(b"")
+    );
+
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        credential_scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let signature = sign(secret_key, &date_stamp, region, &string_to_sign);
+
+    let mut url = format!(
+        "wss://{}/mqtt?{}&X-Amz-Signature={}",
+        endpoint, canonical_query_string, signature
+    );
+    if let Some(token) = session_token {
+        url.push_str(&format!("&X-Amz-Security-Token={}", uri_encode(token)));
+    }
+    url
+}
+
+fn sign(secret_key: &str, date_stamp: &str, region: &str, string_to_sign: &str) -> String {
+    let k_date = hmac_sign(format!("AWS4{}", secret_key).as_bytes(), date_stamp.as_bytes());
+    let k_region = hmac_sign(&k_date, region.as_bytes());
+    let k_service = hmac_sign(&k_region, SERVICE.as_bytes());
+    let k_signing = hmac_sign(&k_service, b"aws4_request");
+    hex::encode(hmac_sign(&k_signing, string_to_sign.as_bytes()))
+}
+
+fn hmac_sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(digest::digest(&digest::SHA256, data))
+}
+
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn sha256_hex_of_empty_payload_matches_known_vector() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn uri_encode_leaves_unreserved_characters_untouched() {
+        assert_eq!(uri_encode("abcXYZ019-_.~"), "abcXYZ019-_.~");
+    }
+
+    #[test]
+    fn uri_encode_percent_encodes_everything_else() {
+        assert_eq!(uri_encode("a b/c:d"), "a%20b%2Fc%3Ad");
+    }
+
+    #[test]
+    fn presigned_wss_url_has_the_expected_scheme_host_and_query_params() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let url = presigned_wss_url_at(
+            "abc123.iot.us-east-1.amazonaws.com",
+            "us-east-1",
+            "AKIDEXAMPLE",
+            "secretkey",
+            None,
+            now,
+        );
+        let prefix = "wss://abc123.iot.us-east-1.amazonaws.com/mqtt?\
+X-Amz-Algorithm=AWS4-HMAC-SHA256&\
+X-Amz-Credential=AKIDEXAMPLE%2F20240101%2Fus-east-1%2Fiotdevicegateway%2Faws4_request&\
+X-Amz-Date=20240101T000000Z&\
+X-Amz-SignedHeaders=host&\
+X-Amz-Signature=";
+        assert!(url.starts_with(prefix), "unexpected url: {}", url);
+        let signature = &url[prefix.len()..];
+        assert_eq!(signature.len(), 64);
+        assert!(signature.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn presigned_wss_url_is_deterministic_for_the_same_inputs() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let a = presigned_wss_url_at("endpoint", "us-east-1", "AKID", "secret", None, now);
+        let b = presigned_wss_url_at("endpoint", "us-east-1", "AKID", "secret", None, now);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn presigned_wss_url_appends_unsigned_session_token() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let url = presigned_wss_url_at(
+            "abc123.iot.us-east-1.amazonaws.com",
+            "us-east-1",
+            "AKIDEXAMPLE",
+            "secretkey",
+            Some("a-session-token"),
+            now,
+        );
+        assert!(url.ends_with("&X-Amz-Security-Token=a-session-token"));
+    }
+
+    #[test]
+    fn presigned_wss_url_changes_with_secret_key() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let a = presigned_wss_url_at("endpoint", "us-east-1", "AKID", "secret-a", None, now);
+        let b = presigned_wss_url_at("endpoint", "us-east-1", "AKID", "secret-b", None, now);
+        assert_ne!(a, b);
+    }
+}